@@ -1,17 +1,27 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    future::Future,
     io::{stdin, stdout, Write},
     ops::DerefMut,
-    path::Path,
     process::Stdio,
+    sync::Arc,
 };
 
 use anyhow::{anyhow, Context, Result};
 use clap::Parser;
+use futures::stream::{FuturesUnordered, StreamExt};
 use indicatif::{style::ProgressStyle, ProgressBar};
 use regex::{Captures, Regex};
 use sqlx::{query_as, sqlite::SqliteConnectOptions, SqlitePool};
-use tokio::process::Command;
+use tokio::{
+    process::Command,
+    sync::{Mutex, Semaphore},
+};
+
+mod native;
+mod state;
+
+use state::{hash_file, Phase, State};
 
 #[derive(Parser)]
 struct Args {
@@ -22,6 +32,26 @@ struct Args {
     /// Absolute location of the target directory
     #[clap(short, long)]
     target_dir: String,
+
+    /// Number of exports to run concurrently
+    #[clap(short, long, default_value_t = num_cpus::get())]
+    jobs: usize,
+
+    /// Which converter to use for turning org source into Markdown
+    #[clap(long, value_enum, default_value_t = Backend::Emacs)]
+    backend: Backend,
+}
+
+/// Which converter `export` uses to turn a node's org source into Markdown.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum Backend {
+    /// Shell out to Emacs with `ox-gfm` and the user's `init.el`, as before. Slower, but
+    /// respects any custom export hooks the user's Emacs config defines.
+    Emacs,
+    /// Parse the org source directly in Rust and render it to GFM. No external
+    /// dependencies and orders of magnitude faster, at the cost of not running the
+    /// user's own Emacs export hooks.
+    Native,
 }
 
 #[tokio::main]
@@ -37,42 +67,218 @@ async fn main() -> Result<()> {
     }
 
     print!("Collecting nodes...");
-    let nodes = get_nodes(&args.db)
-        .await
-        .context("failed to load org-roam nodes")?;
+    let nodes = Arc::new(
+        get_nodes(&args.db)
+            .await
+            .context("failed to load org-roam nodes")?,
+    );
+    let node_list: Arc<Vec<Node>> = Arc::new(nodes.values().cloned().collect());
+
+    let state = Arc::new(Mutex::new(
+        State::load(&args.target_dir)
+            .await
+            .context("failed to load checkpoint state")?,
+    ));
+    let semaphore = Arc::new(Semaphore::new(args.jobs));
 
     let progress_bar_style = "{msg} {bar:40.cyan/blue} {pos}/{len} | {eta} remaining";
 
-    let progress_bar = ProgressBar::new(nodes.len() as u64)
+    // Several org-roam nodes (a file's level-0 node plus each of its headings) share the
+    // same underlying `.org` file, so patch each distinct file exactly once rather than
+    // racing N concurrent read-modify-write passes over it.
+    let mut file_node_ids: HashMap<String, Vec<String>> = HashMap::new();
+    for node in node_list.iter() {
+        file_node_ids.entry(node.file.clone()).or_default().push(node.id.clone());
+    }
+    let files: Arc<Vec<String>> = Arc::new(file_node_ids.keys().cloned().collect());
+    let file_node_ids = Arc::new(file_node_ids);
+
+    let progress_bar = ProgressBar::new(node_list.len() as u64)
         .with_message("Patching node links")
         .with_style(ProgressStyle::default_bar().template(progress_bar_style)?);
-    for node in nodes.values() {
-        patch_links(node, &nodes)
-            .await
-            .context("failed to patch links")?;
-        progress_bar.inc(1);
-    }
+    let patch_results = run_bounded(files.len(), |i| {
+        let files = files.clone();
+        let file_node_ids = file_node_ids.clone();
+        let nodes = nodes.clone();
+        let state = state.clone();
+        let semaphore = semaphore.clone();
+        let progress_bar = progress_bar.clone();
+        async move {
+            let file = &files[i];
+            let node_ids = &file_node_ids[file];
+            process_patch_phase(file, node_ids, &nodes, &state, semaphore, &progress_bar).await
+        }
+    })
+    .await;
+
+    // A file whose links failed to patch still shouldn't stop the rest of the run: we
+    // collect the error but skip exporting every node that lives in that file, so one bad
+    // file doesn't waste the patching (or exporting) work already done on every other one.
+    let failed_patch: Arc<HashSet<String>> = Arc::new(
+        files
+            .iter()
+            .zip(&patch_results)
+            .filter(|(_, result)| result.is_err())
+            .flat_map(|(file, _)| file_node_ids[file].iter().cloned())
+            .collect(),
+    );
+    let mut errors: Vec<anyhow::Error> = patch_results.into_iter().filter_map(Result::err).collect();
 
     progress_bar.reset();
     progress_bar.set_message("Exporting nodes");
 
-    for node in nodes.values() {
-        export(&args.target_dir, node)
+    let export_results = run_bounded(node_list.len(), |i| {
+        let node_list = node_list.clone();
+        let target_dir = args.target_dir.clone();
+        let backend = args.backend;
+        let state = state.clone();
+        let semaphore = semaphore.clone();
+        let progress_bar = progress_bar.clone();
+        let failed_patch = failed_patch.clone();
+        async move {
+            let node = &node_list[i];
+            if failed_patch.contains(&node.id) {
+                println!("Skipping export of {}: link-patching failed", node.title);
+                progress_bar.inc(1);
+                return Ok(());
+            }
+            process_phase(node, Phase::Exported, &state, semaphore, &progress_bar, || {
+                export(&target_dir, node, backend)
+            })
             .await
-            .context("failed to export node")?;
-        progress_bar.inc(1);
-    }
+        }
+    })
+    .await;
     progress_bar.finish_and_clear();
+    errors.extend(export_results.into_iter().filter_map(Result::err));
+
+    report_errors(&errors)?;
+
+    Ok(())
+}
+
+/// Runs `len` indices through `make_task`, at most `Semaphore`-gated concurrency at a time
+/// (the gating happens inside each task via the shared semaphore), and returns every task's
+/// result indexed the same way `make_task` was called, so a failure doesn't stop the rest
+/// of the batch and callers can tell exactly which index failed.
+async fn run_bounded<F, Fut>(len: usize, make_task: F) -> Vec<Result<()>>
+where
+    F: Fn(usize) -> Fut,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    let mut tasks = FuturesUnordered::new();
+    for i in 0..len {
+        let task = tokio::spawn(make_task(i));
+        tasks.push(async move { (i, task.await) });
+    }
+
+    let mut results: Vec<Option<Result<()>>> = (0..len).map(|_| None).collect();
+    while let Some((i, result)) = tasks.next().await {
+        results[i] = Some(match result {
+            Ok(inner) => inner,
+            Err(e) => Err(anyhow!(e)),
+        });
+    }
+    results
+        .into_iter()
+        .map(|r| r.expect("every index is completed exactly once"))
+        .collect()
+}
+
+/// Runs a single node through one phase of the pipeline, gated by `semaphore`, skipping it
+/// if the checkpointed state shows it already completed this phase, and recording progress
+/// (and advancing `progress_bar`) once it's done.
+async fn process_phase<F, Fut>(
+    node: &Node,
+    phase: Phase,
+    state: &Mutex<State>,
+    semaphore: Arc<Semaphore>,
+    progress_bar: &ProgressBar,
+    action: F,
+) -> Result<()>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let _permit = semaphore
+        .acquire_owned()
+        .await
+        .expect("semaphore was closed");
+
+    let source_hash = hash_file(&node.file).await?;
+    let already_done = state.lock().await.is_complete(&node.id, phase, &source_hash);
+    if !already_done {
+        action().await?;
+        let new_hash = hash_file(&node.file).await?;
+        state.lock().await.record(&node.id, phase, new_hash).await?;
+    }
+
+    progress_bar.inc(1);
+    Ok(())
+}
 
+/// Patches links in a single shared `.org` file, gated by `semaphore`, skipping it only if
+/// every node living in that file has already completed link-patching with an unchanged
+/// hash. Records the same completion against every one of `node_ids` once patching
+/// succeeds, since multiple org-roam nodes (a file's level-0 node plus each of its
+/// headings) can share one file.
+async fn process_patch_phase(
+    file: &str,
+    node_ids: &[String],
+    nodes: &HashMap<String, Node>,
+    state: &Mutex<State>,
+    semaphore: Arc<Semaphore>,
+    progress_bar: &ProgressBar,
+) -> Result<()> {
+    let _permit = semaphore
+        .acquire_owned()
+        .await
+        .expect("semaphore was closed");
+
+    let source_hash = hash_file(file).await?;
+    let already_done = {
+        let state = state.lock().await;
+        node_ids
+            .iter()
+            .all(|id| state.is_complete(id, Phase::LinksPatched, &source_hash))
+    };
+    if !already_done {
+        patch_links(file, nodes).await?;
+        let new_hash = hash_file(file).await?;
+        let mut state = state.lock().await;
+        for id in node_ids {
+            state.record(id, Phase::LinksPatched, new_hash.clone()).await?;
+        }
+    }
+
+    progress_bar.inc(node_ids.len() as u64);
     Ok(())
 }
 
+/// Reports aggregated node failures, if any, as a single error.
+fn report_errors(errors: &[anyhow::Error]) -> Result<()> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+
+    for error in errors {
+        println!("{error:#}");
+    }
+    Err(anyhow!("{} node(s) failed to convert", errors.len()))
+}
+
 #[derive(Clone, Debug, sqlx::FromRow)]
-struct Node {
-    id: String,
-    file: String,
-    level: i32,
-    title: String,
+pub(crate) struct Node {
+    pub(crate) id: String,
+    pub(crate) file: String,
+    pub(crate) level: i32,
+    pub(crate) title: String,
+    #[sqlx(default)]
+    pub(crate) aliases: Vec<String>,
+    #[sqlx(default)]
+    pub(crate) tags: Vec<String>,
+    #[sqlx(default)]
+    pub(crate) refs: Vec<String>,
 }
 
 impl Node {
@@ -82,16 +288,54 @@ impl Node {
         self.file = self.file.replace('"', "");
         self.id = self.id.replace('"', "");
     }
+
+    /// Renders the YAML frontmatter block prepended to this node's exported Markdown file.
+    fn frontmatter(&self) -> String {
+        let mut out = String::from("---\n");
+        if !self.aliases.is_empty() {
+            out.push_str(&format!("aliases: [{}]\n", quoted_list(&self.aliases)));
+        }
+        if !self.tags.is_empty() {
+            out.push_str(&format!("tags: [{}]\n", quoted_list(&self.tags)));
+        }
+        out.push_str(&format!("id: {}\n", self.id));
+        if let Some(source) = self.refs.first() {
+            out.push_str(&format!("source: {source}\n"));
+        }
+        out.push_str("---\n\n");
+        out
+    }
+}
+
+/// Renders a list of strings as a quoted, comma-separated YAML flow sequence.
+fn quoted_list(items: &[String]) -> String {
+    items
+        .iter()
+        .map(|item| format!("\"{item}\""))
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
-/// Exports a node to Markdown.
-async fn export(target_dir: &str, node: &Node) -> Result<()> {
+/// Exports a node to Markdown using the given `backend`, prepending its frontmatter.
+async fn export(target_dir: &str, node: &Node, backend: Backend) -> Result<()> {
     let target_file = format!("{target_dir}/{}.md", node.title);
 
-    if Path::new(&target_file).exists() {
-        return Ok(());
-    }
+    let body = match backend {
+        Backend::Emacs => export_via_emacs(&target_file, node).await?,
+        Backend::Native => native::render(node)
+            .await
+            .context("failed to render node natively")?,
+    };
 
+    tokio::fs::write(&target_file, format!("{}{body}", node.frontmatter()))
+        .await
+        .context("failed to write exported file")?;
+
+    Ok(())
+}
+
+/// Exports a node to Markdown by shelling out to Emacs, and returns the rendered body.
+async fn export_via_emacs(target_file: &str, node: &Node) -> Result<String> {
     let subtree_only = if node.level == 0 { "nil" } else { "t" };
     let proc = Command::new("emacs")
         .args([
@@ -116,7 +360,9 @@ async fn export(target_dir: &str, node: &Node) -> Result<()> {
         .await?;
 
     if proc.status.success() {
-        Ok(())
+        tokio::fs::read_to_string(target_file)
+            .await
+            .context("failed to read exported file")
     } else {
         println!(
             "Failed to export {}:\n{}",
@@ -127,39 +373,50 @@ async fn export(target_dir: &str, node: &Node) -> Result<()> {
     }
 }
 
-/// Patches links in a node from [[id:<id>][<name>]] to [[<md-file>][<name>]].
-async fn patch_links(node: &Node, nodes: &HashMap<String, Node>) -> Result<()> {
-    let mut contents = tokio::fs::read_to_string(&node.file)
+/// Patches links in an org file from org-roam's `[[id:<id>][<name>]]` into ordinary
+/// org file links (`[[./File.md][name]]`). This keeps the org source valid for the Emacs
+/// backend; the native backend is responsible for turning these into Obsidian wikilinks
+/// when it renders the file, since only it can protect them from Markdown normalization.
+async fn patch_links(file: &str, nodes: &HashMap<String, Node>) -> Result<()> {
+    let mut contents = tokio::fs::read_to_string(file)
         .await
         .context("failed to read original file")?;
 
     let re = Regex::new(r"\[\[id:([0-9A-F-]+?)\]\[([^\]]+?)\]\]")?;
-    // Find the file for the link in nodes.
     contents = re
         .replace_all(&contents, |caps: &Captures| {
             let id = caps.get(1).unwrap().as_str();
             let name = caps.get(2).unwrap().as_str();
-            let target_node = nodes.get(id).unwrap();
-            let target_file = format!("./{}.md", target_node.title.replace(' ', "%20"));
-            format!("[[{target_file}][{name}]]")
+            match nodes.get(id) {
+                Some(target_node) => {
+                    let target_file = format!("./{}.md", target_node.title.replace(' ', "%20"));
+                    format!("[[{target_file}][{name}]]")
+                }
+                None => {
+                    println!("Warning: {file} links to unknown node {id}, leaving link as-is");
+                    caps.get(0).unwrap().as_str().to_string()
+                }
+            }
         })
         .to_string();
 
-    tokio::fs::write(&node.file, contents)
+    tokio::fs::write(file, contents)
         .await
         .context("failed to save patched file")?;
 
     Ok(())
 }
 
-/// Gets all nodes from the org-roam DB and return them as a hashmap keyed by ID.
+/// Gets all nodes from the org-roam DB, together with their aliases, tags and refs, and
+/// returns them as a hashmap keyed by ID.
 async fn get_nodes(db: &str) -> Result<HashMap<String, Node>> {
     let pool = SqlitePool::connect_with(SqliteConnectOptions::new().filename(db))
         .await
         .context("failed to open org-roam SQLite database")?;
+    let mut conn = pool.acquire().await?;
 
     let mut rows = query_as::<_, Node>("SELECT id, file, level, title FROM nodes")
-        .fetch_all(pool.acquire().await?.deref_mut())
+        .fetch_all(conn.deref_mut())
         .await
         .context("failed to query org-roam SQLite database")?;
 
@@ -167,5 +424,37 @@ async fn get_nodes(db: &str) -> Result<HashMap<String, Node>> {
         row.cleanup();
     }
 
-    Ok(rows.iter().map(|n| (n.id.clone(), n.clone())).collect())
+    let mut nodes: HashMap<String, Node> = rows.into_iter().map(|n| (n.id.clone(), n)).collect();
+
+    let aliases = query_as::<_, (String, String)>("SELECT node_id, alias FROM aliases")
+        .fetch_all(conn.deref_mut())
+        .await
+        .context("failed to query org-roam aliases")?;
+    for (node_id, alias) in aliases {
+        if let Some(node) = nodes.get_mut(&node_id.replace('"', "")) {
+            node.aliases.push(alias.replace('"', ""));
+        }
+    }
+
+    let tags = query_as::<_, (String, String)>("SELECT node_id, tag FROM tags")
+        .fetch_all(conn.deref_mut())
+        .await
+        .context("failed to query org-roam tags")?;
+    for (node_id, tag) in tags {
+        if let Some(node) = nodes.get_mut(&node_id.replace('"', "")) {
+            node.tags.push(tag.replace('"', ""));
+        }
+    }
+
+    let refs = query_as::<_, (String, String)>("SELECT node_id, ref FROM refs")
+        .fetch_all(conn.deref_mut())
+        .await
+        .context("failed to query org-roam refs")?;
+    for (node_id, node_ref) in refs {
+        if let Some(node) = nodes.get_mut(&node_id.replace('"', "")) {
+            node.refs.push(node_ref.replace('"', ""));
+        }
+    }
+
+    Ok(nodes)
 }
@@ -0,0 +1,210 @@
+use anyhow::{Context, Result};
+use comrak::{format_commonmark, nodes::AstNode, parse_document, Arena, Options};
+use regex::{Captures, Regex};
+
+use crate::Node;
+
+/// Renders a node's org subtree as GitHub-Flavored Markdown without shelling out to Emacs.
+///
+/// This is a best-effort translation of the org constructs the converter actually emits:
+/// headline levels, `#+title`, `#+begin_src`/`#+end_src` blocks, bold/italic/verbatim
+/// markup, lists, and the `[[./File.md][name]]` file links `patch_links` has already
+/// produced, which this backend turns into Obsidian `[[Title|name]]` wikilinks. The rough
+/// translation is then re-serialized through comrak's CommonMark formatter, which
+/// normalizes it into well-formed GFM.
+pub async fn render(node: &Node) -> Result<String> {
+    let source = tokio::fs::read_to_string(&node.file)
+        .await
+        .context("failed to read original file for native export")?;
+
+    let subtree = subtree_for(&source, node);
+    normalize(&translate(subtree))
+}
+
+/// Slices out just the node's own subtree for `level > 0` nodes, located via its `:ID:`
+/// property drawer, mirroring the `subtree-only` export Emacs performs. Level-0 (file)
+/// nodes use the whole file. Falls back to the whole file if the node's heading can't be
+/// found, rather than failing the export outright.
+fn subtree_for<'a>(source: &'a str, node: &Node) -> &'a str {
+    if node.level == 0 {
+        return source;
+    }
+
+    // Pair each line's content (terminator stripped, for matching) with the real byte
+    // offset it starts at, so slicing below doesn't assume a fixed terminator width
+    // (CRLF files would otherwise drift the reconstructed offsets out of bounds).
+    let mut offset = 0;
+    let lines: Vec<(usize, &str)> = source
+        .split_inclusive('\n')
+        .map(|raw| {
+            let start = offset;
+            offset += raw.len();
+            (start, raw.trim_end_matches(['\r', '\n']))
+        })
+        .collect();
+
+    let id_needle = format!(":ID: {}", node.id);
+    let Some(id_line) = lines.iter().position(|(_, l)| l.contains(&id_needle)) else {
+        return source;
+    };
+    let Some(heading_line) = (0..id_line).rev().find(|&i| leading_stars(lines[i].1).is_some())
+    else {
+        return source;
+    };
+
+    let level = leading_stars(lines[heading_line].1).expect("checked above");
+    let end = lines[heading_line + 1..]
+        .iter()
+        .position(|(_, l)| leading_stars(l).is_some_and(|stars| stars <= level))
+        .map(|i| heading_line + 1 + i)
+        .unwrap_or(lines.len());
+
+    let start_offset = lines[heading_line].0;
+    let end_offset = lines.get(end).map(|(offset, _)| *offset).unwrap_or(source.len());
+    &source[start_offset..end_offset]
+}
+
+/// Returns the number of leading `*` characters if `line` is an org headline, i.e. the
+/// stars are followed by a space.
+fn leading_stars(line: &str) -> Option<usize> {
+    let trimmed = line.trim_start();
+    let stars = trimmed.chars().take_while(|c| *c == '*').count();
+    (stars > 0 && trimmed.as_bytes().get(stars) == Some(&b' ')).then_some(stars)
+}
+
+/// Translates org markup into a rough Markdown approximation. The output doesn't need to
+/// be perfectly well-formed, since `normalize` re-serializes it afterwards.
+fn translate(org: &str) -> String {
+    let bold = Regex::new(r"\*([^*\n]+)\*").unwrap();
+    // Org only treats `/.../` as emphasis when the opening `/` is preceded by
+    // whitespace/`(`/line-start and the closing `/` is followed by whitespace/
+    // punctuation/line-end. Without that, bare `/` in dates, fractions, and URLs
+    // (`12/25/2024`, `http://x/y/z`) would get corrupted into underscores.
+    let italic = Regex::new(r"(^|[\s(])/([^/\s][^/\n]*?)/([\s).,:;!?]|$)").unwrap();
+    let verbatim = Regex::new(r"[=~]([^=~\n]+)[=~]").unwrap();
+    let drawer_line = Regex::new(r"^:[^:\s]+:(\s|$)").unwrap();
+    let file_link = Regex::new(r"\[\[([^\]]+)\]\[([^\]]+)\]\]").unwrap();
+
+    let mut out = String::new();
+    let mut in_src = false;
+    let mut in_drawer = false;
+
+    for line in org.lines() {
+        let trimmed = line.trim_start();
+
+        if in_src {
+            if trimmed.eq_ignore_ascii_case("#+end_src") {
+                in_src = false;
+                out.push_str("```\n");
+            } else {
+                out.push_str(line);
+                out.push('\n');
+            }
+            continue;
+        }
+
+        if in_drawer {
+            if trimmed.eq_ignore_ascii_case(":end:") {
+                in_drawer = false;
+            }
+            continue;
+        }
+        if trimmed.eq_ignore_ascii_case(":properties:") {
+            in_drawer = true;
+            continue;
+        }
+        if drawer_line.is_match(trimmed) {
+            // A stray drawer line outside :PROPERTIES:...:END: (e.g. :ROAM_REFS:), or an
+            // :END: with no matching opener. Org-roam's own :ID:/:ROAM_REFS: drawers are
+            // metadata already captured in the YAML frontmatter, so drop them here too.
+            continue;
+        }
+
+        if let Some(lang) = strip_prefix_ci(trimmed, "#+begin_src") {
+            in_src = true;
+            out.push_str(&format!("```{}\n", lang.trim()));
+            continue;
+        }
+        if let Some(stars) = leading_stars(trimmed) {
+            out.push_str(&format!("{} {}\n", "#".repeat(stars), trimmed[stars..].trim()));
+            continue;
+        }
+        if let Some(title) = strip_prefix_ci(trimmed, "#+title:") {
+            out.push_str(&format!("# {}\n", title.trim()));
+            continue;
+        }
+        if trimmed.starts_with("#+") {
+            // Other org keywords (#+filetags, #+options, ...) have no GFM equivalent.
+            continue;
+        }
+
+        let mut text = line.to_string();
+        text = file_link
+            .replace_all(&text, |caps: &Captures| {
+                let path = &caps[1];
+                let name = &caps[2];
+                match wikilink_title(path) {
+                    Some(title) => format!("[[{title}|{name}]]"),
+                    None => format!("[{name}]({path})"),
+                }
+            })
+            .to_string();
+        text = bold.replace_all(&text, "**$1**").to_string();
+        text = italic.replace_all(&text, "$1_$2_$3").to_string();
+        text = verbatim.replace_all(&text, "`$1`").to_string();
+        out.push_str(&text);
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Case-insensitive `strip_prefix` that returns the rest of the line.
+fn strip_prefix_ci<'a>(line: &'a str, prefix: &str) -> Option<&'a str> {
+    line.get(..prefix.len())
+        .filter(|head| head.eq_ignore_ascii_case(prefix))
+        .map(|_| &line[prefix.len()..])
+}
+
+/// Recovers a node title from the relative `./Title.md` path `patch_links` writes, so its
+/// org file links can be rendered as Obsidian wikilinks. Returns `None` for anything that
+/// isn't one of those local file links (external URLs, for instance).
+fn wikilink_title(path: &str) -> Option<String> {
+    let title = path.strip_prefix("./")?.strip_suffix(".md")?;
+    Some(title.replace("%20", " "))
+}
+
+/// Re-serializes `markdown` through comrak's CommonMark formatter to guarantee
+/// well-formed GFM output.
+///
+/// Comrak doesn't know about Obsidian's `[[wikilink]]` syntax and, since a bare `[[` isn't
+/// meaningful CommonMark, escapes the brackets as literal `\[\[` text when it re-serializes
+/// — which Obsidian then doesn't recognize as a link. Wikilink spans are swapped out for
+/// inert placeholders before normalizing and restored verbatim afterwards to avoid that.
+fn normalize(markdown: &str) -> Result<String> {
+    let wikilink = Regex::new(r"\[\[([^\[\]]+)\]\]").unwrap();
+    let mut placeholders = Vec::new();
+    let protected = wikilink.replace_all(markdown, |caps: &Captures| {
+        let token = format!("\u{E000}{}\u{E001}", placeholders.len());
+        placeholders.push(caps[0].to_string());
+        token
+    });
+
+    let arena = Arena::new();
+    let mut options = Options::default();
+    options.extension.table = true;
+    options.extension.strikethrough = true;
+
+    let root: &AstNode = parse_document(&arena, &protected, &options);
+
+    let mut buf = Vec::new();
+    format_commonmark(root, &options, &mut buf).context("failed to normalize native markdown")?;
+    let mut rendered =
+        String::from_utf8(buf).context("native markdown output was not valid UTF-8")?;
+
+    for (i, original) in placeholders.iter().enumerate() {
+        rendered = rendered.replace(&format!("\u{E000}{i}\u{E001}"), original);
+    }
+
+    Ok(rendered)
+}
@@ -0,0 +1,90 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Name of the checkpoint file written inside the target directory.
+const STATE_FILE_NAME: &str = ".o2o-state";
+
+/// How far a node has progressed through the conversion pipeline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Phase {
+    LinksPatched,
+    Exported,
+}
+
+/// Checkpointed progress for a single node.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct NodeState {
+    phase: Phase,
+    /// SHA-256 hash of the source `.org` file's contents as of `phase` completing.
+    source_hash: String,
+}
+
+/// Persisted, per-node conversion progress, used to make interrupted runs resumable.
+///
+/// Loaded once at startup and flushed to `<target_dir>/.o2o-state` after every node that
+/// completes a phase, so a killed or interrupted run can pick up where it left off.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    nodes: HashMap<String, NodeState>,
+    #[serde(skip)]
+    path: String,
+}
+
+impl State {
+    /// Loads state from `<target_dir>/.o2o-state`, or starts fresh if it doesn't exist yet.
+    pub async fn load(target_dir: &str) -> Result<Self> {
+        let path = format!("{target_dir}/{STATE_FILE_NAME}");
+
+        let mut state = if Path::new(&path).exists() {
+            let bytes = tokio::fs::read(&path)
+                .await
+                .context("failed to read state file")?;
+            rmp_serde::from_slice(&bytes).context("failed to deserialize state file")?
+        } else {
+            State::default()
+        };
+        state.path = path;
+
+        Ok(state)
+    }
+
+    /// Returns true if `node_id` has already reached at least `phase` with an unchanged
+    /// source file, meaning that phase can be skipped on this run.
+    pub fn is_complete(&self, node_id: &str, phase: Phase, source_hash: &str) -> bool {
+        self.nodes
+            .get(node_id)
+            .is_some_and(|n| n.phase >= phase && n.source_hash == source_hash)
+    }
+
+    /// Records that `node_id` has reached `phase` with the given source hash, and flushes
+    /// the state file immediately so the checkpoint survives a crash.
+    ///
+    /// The flush writes to a `.tmp` sibling and renames it over the real path, so a crash
+    /// mid-write can never leave a truncated, undeserializable state file behind.
+    pub async fn record(&mut self, node_id: &str, phase: Phase, source_hash: String) -> Result<()> {
+        self.nodes
+            .insert(node_id.to_string(), NodeState { phase, source_hash });
+
+        let bytes = rmp_serde::to_vec(self).context("failed to serialize state")?;
+        let tmp_path = format!("{}.tmp", self.path);
+        tokio::fs::write(&tmp_path, bytes)
+            .await
+            .context("failed to write temporary state file")?;
+        tokio::fs::rename(&tmp_path, &self.path)
+            .await
+            .context("failed to finalize state file")?;
+
+        Ok(())
+    }
+}
+
+/// Hashes the contents of `path` with SHA-256 and returns the result as a hex string.
+pub async fn hash_file(path: &str) -> Result<String> {
+    let contents = tokio::fs::read(path)
+        .await
+        .context("failed to read file for hashing")?;
+    Ok(format!("{:x}", Sha256::digest(&contents)))
+}